@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use crate::controller::ControlGains;
+
+/// Which [`ControlGains`] variant an [`AutoTuner`] should emit once it
+/// converges
+pub enum AutoTuneTarget {
+    PI,
+    PID,
+}
+
+/// The result of feeding one tick into an in-progress auto-tune
+pub enum AutoTuneStatus {
+    InProgress,
+    Converged(ControlGains),
+    TimedOut,
+}
+
+/// Derives [`ControlGains`] with a relay-feedback experiment, per the
+/// Ziegler-Nichols method referenced by
+/// [`CylinderPositionController::new`](crate::controller::CylinderPositionController::new)
+///
+/// Drive the plant with [`Self::relay_output`], feed the resulting
+/// measurement into [`Self::step`] each tick, and once the process
+/// settles into a stable limit cycle the ultimate gain `Ku` and
+/// ultimate period `Tu` are measured from the oscillation and used to
+/// emit a ready-to-use [`ControlGains`] from the classic ZN table.
+///
+/// For a primer on relay-feedback tuning, see
+/// <https://en.wikipedia.org/wiki/Ziegler%E2%80%93Nichols_method#Relay_method>
+pub struct AutoTuner {
+    target: AutoTuneTarget,
+    setpoint: f64,
+    relay_amplitude: f64,
+    hysteresis: f64,
+    cycles_to_average: usize,
+    timeout: f64,
+
+    relay_high: bool,
+    elapsed: f64,
+    half_cycle_extreme: f64,
+    switch_times: Vec<f64>,
+    half_cycle_amplitudes: Vec<f64>,
+}
+
+impl AutoTuner {
+    /// Set up a relay-feedback experiment around `setpoint`
+    ///
+    /// `relay_amplitude` (`d` in the ZN relay method) is the size of
+    /// the bang-bang output step, and `hysteresis` is the band around
+    /// `setpoint` the measurement must cross before the relay
+    /// switches, which rejects sensor noise that would otherwise
+    /// cause spurious switches. `cycles_to_average` full oscillations
+    /// are measured and averaged before the tuner declares
+    /// convergence; it's clamped to a minimum of 2, since each period
+    /// is measured across three consecutive relay switches and a
+    /// single oscillation only records two. `timeout`
+    /// bounds how long it waits for a stable oscillation to develop
+    /// before giving up.
+    pub fn new(
+        setpoint: f64,
+        relay_amplitude: f64,
+        hysteresis: f64,
+        target: AutoTuneTarget,
+        cycles_to_average: usize,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            target,
+            setpoint,
+            relay_amplitude,
+            hysteresis,
+            cycles_to_average: cycles_to_average.max(2),
+            timeout: timeout.as_secs_f64(),
+            relay_high: true,
+            elapsed: 0.0,
+            half_cycle_extreme: setpoint,
+            switch_times: Vec::new(),
+            half_cycle_amplitudes: Vec::new(),
+        }
+    }
+
+    /// The bang-bang relay drive to apply this tick
+    ///
+    /// Feed the measurement that results from applying this back into
+    /// [`Self::step`].
+    pub fn relay_output(&self) -> f64 {
+        if self.relay_high {
+            self.relay_amplitude
+        } else {
+            -self.relay_amplitude
+        }
+    }
+
+    /// Advance the experiment by one tick of `dt` seconds
+    ///
+    /// Returns [`AutoTuneStatus::InProgress`] while the relay is
+    /// still oscillating, [`AutoTuneStatus::Converged`] with the
+    /// derived gains once enough cycles have been averaged, or
+    /// [`AutoTuneStatus::TimedOut`] if no stable oscillation develops
+    /// before the configured timeout.
+    pub fn step(&mut self, measurement: f64, dt: f64) -> AutoTuneStatus {
+        self.elapsed += dt;
+        if self.elapsed > self.timeout {
+            return AutoTuneStatus::TimedOut;
+        }
+
+        if self.relay_high {
+            self.half_cycle_extreme = self.half_cycle_extreme.max(measurement);
+        } else {
+            self.half_cycle_extreme = self.half_cycle_extreme.min(measurement);
+        }
+
+        let should_switch = if self.relay_high {
+            measurement > self.setpoint + self.hysteresis
+        } else {
+            measurement < self.setpoint - self.hysteresis
+        };
+        if !should_switch {
+            return AutoTuneStatus::InProgress;
+        }
+
+        self.half_cycle_amplitudes
+            .push((self.half_cycle_extreme - self.setpoint).abs());
+        self.switch_times.push(self.elapsed);
+        self.relay_high = !self.relay_high;
+        self.half_cycle_extreme = self.setpoint;
+
+        // A full oscillation is two half-cycles; wait for enough of
+        // them before averaging to declare convergence.
+        if self.half_cycle_amplitudes.len() < 2 * self.cycles_to_average {
+            return AutoTuneStatus::InProgress;
+        }
+
+        AutoTuneStatus::Converged(self.converged_gains())
+    }
+
+    /// Average the recorded oscillation into `Ku`/`Tu` and emit gains
+    /// from the classic Ziegler-Nichols relay table
+    fn converged_gains(&self) -> ControlGains {
+        let ultimate_period = self
+            .switch_times
+            .windows(3)
+            .map(|window| window[2] - window[0])
+            .sum::<f64>()
+            / (self.switch_times.len() - 2) as f64;
+
+        let ultimate_amplitude = self.half_cycle_amplitudes.iter().sum::<f64>()
+            / self.half_cycle_amplitudes.len() as f64;
+        let ultimate_gain =
+            4.0 * self.relay_amplitude / (std::f64::consts::PI * ultimate_amplitude);
+
+        match self.target {
+            AutoTuneTarget::PI => {
+                let k_p = 0.45 * ultimate_gain;
+                ControlGains::PI(k_p, k_p / (0.83 * ultimate_period))
+            }
+            AutoTuneTarget::PID => {
+                let k_p = 0.6 * ultimate_gain;
+                ControlGains::PID(
+                    k_p,
+                    k_p / (0.5 * ultimate_period),
+                    k_p * 0.125 * ultimate_period,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner(cycles_to_average: usize, timeout: Duration) -> AutoTuner {
+        AutoTuner::new(
+            0.0,
+            1.0,
+            0.01,
+            AutoTuneTarget::PID,
+            cycles_to_average,
+            timeout,
+        )
+    }
+
+    #[test]
+    fn relay_starts_high_and_holds_within_the_hysteresis_band() {
+        let mut tuner = tuner(2, Duration::from_secs(60));
+        assert_eq!(tuner.relay_output(), 1.0);
+        assert!(matches!(tuner.step(0.005, 0.1), AutoTuneStatus::InProgress));
+        assert_eq!(tuner.relay_output(), 1.0);
+    }
+
+    #[test]
+    fn relay_switches_once_measurement_crosses_the_hysteresis_band() {
+        let mut tuner = tuner(2, Duration::from_secs(60));
+        assert!(matches!(tuner.step(0.5, 0.1), AutoTuneStatus::InProgress));
+        assert_eq!(tuner.relay_output(), -1.0);
+    }
+
+    #[test]
+    fn times_out_without_a_stable_oscillation() {
+        let mut tuner = tuner(2, Duration::from_millis(50));
+        assert!(matches!(tuner.step(0.0, 1.0), AutoTuneStatus::TimedOut));
+    }
+
+    #[test]
+    fn converges_to_ziegler_nichols_pid_gains_from_a_synthetic_square_wave() {
+        let mut tuner = tuner(2, Duration::from_secs(60));
+        let mut status = AutoTuneStatus::InProgress;
+        for measurement in [0.5, -0.5, 0.5, -0.5] {
+            status = tuner.step(measurement, 0.1);
+        }
+        let gains = match status {
+            AutoTuneStatus::Converged(gains) => gains,
+            _ => panic!("expected convergence after four half-cycles"),
+        };
+        let ControlGains::PID(k_p, k_i, k_d) = gains else {
+            panic!("expected PID gains");
+        };
+
+        // Four half-cycles of amplitude 0.5 switching every 0.1s give
+        // Ku = 4d/(pi*a) and Tu = 0.2s.
+        let ultimate_gain = 4.0 * 1.0 / (std::f64::consts::PI * 0.5);
+        let ultimate_period = 0.2;
+        let expected_k_p = 0.6 * ultimate_gain;
+        assert!((k_p - expected_k_p).abs() < 1e-9);
+        assert!((k_i - expected_k_p / (0.5 * ultimate_period)).abs() < 1e-9);
+        assert!((k_d - expected_k_p * 0.125 * ultimate_period).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cycles_to_average_below_two_is_clamped_and_does_not_divide_by_zero() {
+        let mut tuner = tuner(1, Duration::from_secs(60));
+        let mut status = AutoTuneStatus::InProgress;
+        for measurement in [0.5, -0.5, 0.5, -0.5] {
+            status = tuner.step(measurement, 0.1);
+        }
+        let gains = match status {
+            AutoTuneStatus::Converged(gains) => gains,
+            _ => panic!("expected convergence after four half-cycles"),
+        };
+        let ControlGains::PID(k_p, k_i, k_d) = gains else {
+            panic!("expected PID gains");
+        };
+        assert!(k_p.is_finite() && k_i.is_finite() && k_d.is_finite());
+    }
+}
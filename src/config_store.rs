@@ -0,0 +1,486 @@
+use crate::controller::ControlGains;
+use crate::position_sensor::Calibration;
+
+/// Max calibration table entries a [`PersistedConfig`] can carry;
+/// kept small and fixed so the serialized blob is a fixed,
+/// page-friendly size for flash storage.
+pub const MAX_CALIBRATION_POINTS: usize = 8;
+
+/// Bumped whenever [`PersistedConfig`]'s on-disk layout changes, so a
+/// blob written by an older firmware is recognized as stale rather
+/// than misinterpreted.
+const CONFIG_VERSION: u16 = 1;
+
+/// The serialized size of a [`PersistedConfig`] blob, in bytes
+pub const CONFIG_BLOB_LEN: usize = 2 // version
+    + 1 // gains tag
+    + 8 * 3 // k_p, k_i, k_d
+    + 8 // setpoint
+    + 8 * 2 // output_clamp
+    + 8 * 2 // accumulator_clamp
+    + 1 // calibration_len
+    + 16 * MAX_CALIBRATION_POINTS // calibration_table
+    + 1 // has_polynomial
+    + 8 * 4 // polynomial
+    + 4; // crc32
+
+/// Everything about the control loop that should survive a power
+/// cycle: the active gains, setpoint, output/accumulator clamps, and
+/// sensor calibration
+///
+/// Serializes to a fixed-size blob (see [`CONFIG_BLOB_LEN`]) tagged
+/// with a version and a CRC-32, so a [`ConfigStore`] can recognize a
+/// corrupt or out-of-date blob and fall back to compiled-in
+/// defaults instead of loading garbage.
+#[derive(Clone)]
+pub struct PersistedConfig {
+    pub gains: ControlGains,
+    pub setpoint: f64,
+    pub output_clamp: (f64, f64),
+    pub accumulator_clamp: (f64, f64),
+    calibration_table: [(f64, f64); MAX_CALIBRATION_POINTS],
+    calibration_len: usize,
+    pub polynomial_correction: Option<[f64; 4]>,
+}
+
+impl PersistedConfig {
+    /// Build a config from its parts
+    ///
+    /// Panics if `calibration_table` has more than
+    /// [`MAX_CALIBRATION_POINTS`] entries.
+    pub fn new(
+        gains: ControlGains,
+        setpoint: f64,
+        output_clamp: (f64, f64),
+        accumulator_clamp: (f64, f64),
+        calibration_table: &[(f64, f64)],
+        polynomial_correction: Option<[f64; 4]>,
+    ) -> Self {
+        assert!(
+            calibration_table.len() <= MAX_CALIBRATION_POINTS,
+            "calibration table exceeds MAX_CALIBRATION_POINTS"
+        );
+        let mut table = [(0.0, 0.0); MAX_CALIBRATION_POINTS];
+        table[..calibration_table.len()].copy_from_slice(calibration_table);
+        Self {
+            gains,
+            setpoint,
+            output_clamp,
+            accumulator_clamp,
+            calibration_table: table,
+            calibration_len: calibration_table.len(),
+            polynomial_correction,
+        }
+    }
+
+    /// Build the [`Calibration`] this config describes
+    pub fn calibration(&self) -> Calibration {
+        let mut calibration =
+            Calibration::new(self.calibration_table[..self.calibration_len].to_vec());
+        if let Some(polynomial) = self.polynomial_correction {
+            calibration.set_polynomial_correction(polynomial);
+        }
+        calibration
+    }
+
+    fn to_bytes(&self) -> [u8; CONFIG_BLOB_LEN] {
+        let mut bytes = [0u8; CONFIG_BLOB_LEN];
+        let mut cursor = 0;
+        let mut put = |field: &[u8]| {
+            bytes[cursor..cursor + field.len()].copy_from_slice(field);
+            cursor += field.len();
+        };
+
+        put(&CONFIG_VERSION.to_le_bytes());
+        let (gains_tag, k_p, k_i, k_d) = match self.gains {
+            ControlGains::P(k_p) => (0u8, k_p, 0.0, 0.0),
+            ControlGains::PI(k_p, k_i) => (1u8, k_p, k_i, 0.0),
+            ControlGains::PID(k_p, k_i, k_d) => (2u8, k_p, k_i, k_d),
+        };
+        put(&[gains_tag]);
+        put(&k_p.to_le_bytes());
+        put(&k_i.to_le_bytes());
+        put(&k_d.to_le_bytes());
+        put(&self.setpoint.to_le_bytes());
+        put(&self.output_clamp.0.to_le_bytes());
+        put(&self.output_clamp.1.to_le_bytes());
+        put(&self.accumulator_clamp.0.to_le_bytes());
+        put(&self.accumulator_clamp.1.to_le_bytes());
+        put(&[self.calibration_len as u8]);
+        for (raw, position) in self.calibration_table {
+            put(&raw.to_le_bytes());
+            put(&position.to_le_bytes());
+        }
+        match self.polynomial_correction {
+            Some(coefficients) => {
+                put(&[1]);
+                for coefficient in coefficients {
+                    put(&coefficient.to_le_bytes());
+                }
+            }
+            None => put(&[0; 1 + 8 * 4]),
+        }
+
+        let crc = crc32(&bytes[..cursor]);
+        bytes[cursor..cursor + 4].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Parse a blob written by [`Self::to_bytes`], rejecting it if
+    /// the version tag or CRC don't check out
+    fn from_bytes(bytes: &[u8; CONFIG_BLOB_LEN]) -> Option<Self> {
+        let crc_offset = CONFIG_BLOB_LEN - 4;
+        let stored_crc = u32::from_le_bytes(bytes[crc_offset..].try_into().unwrap());
+        if crc32(&bytes[..crc_offset]) != stored_crc {
+            return None;
+        }
+
+        let mut cursor = 0;
+        let mut take = |len: usize| {
+            let field = &bytes[cursor..cursor + len];
+            cursor += len;
+            field
+        };
+
+        if u16::from_le_bytes(take(2).try_into().unwrap()) != CONFIG_VERSION {
+            return None;
+        }
+        let gains_tag = take(1)[0];
+        let k_p = f64::from_le_bytes(take(8).try_into().unwrap());
+        let k_i = f64::from_le_bytes(take(8).try_into().unwrap());
+        let k_d = f64::from_le_bytes(take(8).try_into().unwrap());
+        let gains = match gains_tag {
+            0 => ControlGains::P(k_p),
+            1 => ControlGains::PI(k_p, k_i),
+            2 => ControlGains::PID(k_p, k_i, k_d),
+            _ => return None,
+        };
+        let setpoint = f64::from_le_bytes(take(8).try_into().unwrap());
+        let output_clamp = (
+            f64::from_le_bytes(take(8).try_into().unwrap()),
+            f64::from_le_bytes(take(8).try_into().unwrap()),
+        );
+        let accumulator_clamp = (
+            f64::from_le_bytes(take(8).try_into().unwrap()),
+            f64::from_le_bytes(take(8).try_into().unwrap()),
+        );
+        let calibration_len = take(1)[0] as usize;
+        if calibration_len > MAX_CALIBRATION_POINTS {
+            return None;
+        }
+        let mut calibration_table = [(0.0, 0.0); MAX_CALIBRATION_POINTS];
+        for entry in &mut calibration_table {
+            *entry = (
+                f64::from_le_bytes(take(8).try_into().unwrap()),
+                f64::from_le_bytes(take(8).try_into().unwrap()),
+            );
+        }
+        let has_polynomial = take(1)[0];
+        let polynomial = std::array::from_fn(|_| f64::from_le_bytes(take(8).try_into().unwrap()));
+        let polynomial_correction = (has_polynomial != 0).then_some(polynomial);
+
+        Some(Self {
+            gains,
+            setpoint,
+            output_clamp,
+            accumulator_clamp,
+            calibration_table,
+            calibration_len,
+            polynomial_correction,
+        })
+    }
+}
+
+/// Low-level non-volatile byte storage a [`ConfigStore`] persists
+/// its serialized blob to
+///
+/// Implementors just move a fixed-size blob of bytes around; the
+/// version tag, CRC, and fallback-to-defaults logic all live in
+/// [`ConfigStore`] itself.
+pub trait ConfigBackend {
+    type Error: core::fmt::Debug;
+
+    fn read(&mut self, buffer: &mut [u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error>;
+    fn write(&mut self, buffer: &[u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error>;
+}
+
+/// Loads and saves a [`PersistedConfig`] through a [`ConfigBackend`]
+pub struct ConfigStore<B: ConfigBackend> {
+    backend: B,
+}
+
+impl<B: ConfigBackend> ConfigStore<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    /// Load the persisted config, falling back to `defaults` if the
+    /// backend can't be read, or the blob it holds is unversioned,
+    /// stale, or corrupt
+    pub fn load(&mut self, defaults: PersistedConfig) -> PersistedConfig {
+        let mut buffer = [0u8; CONFIG_BLOB_LEN];
+        match self.backend.read(&mut buffer) {
+            Ok(()) => PersistedConfig::from_bytes(&buffer).unwrap_or(defaults),
+            Err(_) => defaults,
+        }
+    }
+
+    /// Persist `config` to the backend
+    pub fn save(&mut self, config: &PersistedConfig) -> Result<(), B::Error> {
+        self.backend.write(&config.to_bytes())
+    }
+}
+
+/// Persists the config blob as a flat file, for the desktop ek1100
+/// example
+pub struct FileBackend {
+    path: std::path::PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ConfigBackend for FileBackend {
+    type Error = std::io::Error;
+
+    fn read(&mut self, buffer: &mut [u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+        use std::io::Read;
+        std::fs::File::open(&self.path)?.read_exact(buffer)
+    }
+
+    fn write(&mut self, buffer: &[u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+        use std::io::Write;
+        std::fs::File::create(&self.path)?.write_all(buffer)
+    }
+}
+
+/// The largest blob this module will pad a write out to, to stay a
+/// fixed-size stack buffer regardless of the flash's `WRITE_SIZE`
+///
+/// Comfortably covers common NOR flash write granularities (a few
+/// bytes up to a 4 KiB page); a flash reporting a larger
+/// `WRITE_SIZE` than this is rejected by
+/// [`NorFlashBackendError::WriteSizeUnsupported`] rather than
+/// silently truncated.
+const MAX_PADDED_CONFIG_LEN: usize = 4096;
+
+/// Errors from a [`NorFlashBackend`]
+#[derive(Debug)]
+pub enum NorFlashBackendError<E> {
+    /// The underlying flash operation failed
+    Flash(E),
+    /// The flash's `WRITE_SIZE` is larger than
+    /// [`MAX_PADDED_CONFIG_LEN`], so the blob can't be padded to it
+    WriteSizeUnsupported,
+}
+
+/// Persists the config blob to a region of NOR flash addressed
+/// through [`embedded_storage`]'s
+/// [`NorFlash`](embedded_storage::nor_flash::NorFlash) trait, for
+/// `no_std` targets
+///
+/// Flash requires erasing before writing and writing in
+/// `WRITE_SIZE`-aligned chunks, so a write here erases the whole
+/// `ERASE_SIZE` region starting at `base_address`, then writes the
+/// blob back padded up to the next `WRITE_SIZE` boundary.
+pub struct NorFlashBackend<F> {
+    flash: F,
+    base_address: u32,
+}
+
+impl<F> NorFlashBackend<F> {
+    pub fn new(flash: F, base_address: u32) -> Self {
+        Self {
+            flash,
+            base_address,
+        }
+    }
+}
+
+impl<F: embedded_storage::nor_flash::NorFlash> ConfigBackend for NorFlashBackend<F> {
+    type Error = NorFlashBackendError<F::Error>;
+
+    fn read(&mut self, buffer: &mut [u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+        self.flash
+            .read(self.base_address, buffer)
+            .map_err(NorFlashBackendError::Flash)
+    }
+
+    fn write(&mut self, buffer: &[u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+        // Flash writes must land on WRITE_SIZE boundaries; pad the
+        // blob with zeros out to the next one before writing it.
+        let padded_len = buffer.len().next_multiple_of(F::WRITE_SIZE);
+        if padded_len > MAX_PADDED_CONFIG_LEN {
+            return Err(NorFlashBackendError::WriteSizeUnsupported);
+        }
+
+        self.flash
+            .erase(self.base_address, self.base_address + F::ERASE_SIZE as u32)
+            .map_err(NorFlashBackendError::Flash)?;
+
+        let mut padded = [0u8; MAX_PADDED_CONFIG_LEN];
+        padded[..buffer.len()].copy_from_slice(buffer);
+        self.flash
+            .write(self.base_address, &padded[..padded_len])
+            .map_err(NorFlashBackendError::Flash)
+    }
+}
+
+/// CRC-32 (ISO-HDLC, the same variant used by zlib/PNG), computed
+/// without pulling in an external crate so this module has no
+/// dependency beyond `embedded_storage` for the flash backend
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MemoryBackend {
+        bytes: Option<[u8; CONFIG_BLOB_LEN]>,
+    }
+
+    impl ConfigBackend for MemoryBackend {
+        type Error = std::convert::Infallible;
+
+        fn read(&mut self, buffer: &mut [u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+            match self.bytes {
+                Some(bytes) => {
+                    *buffer = bytes;
+                    Ok(())
+                }
+                None => {
+                    // An erased/unwritten backend; any fixed pattern
+                    // here should still be rejected by the CRC check.
+                    *buffer = [0xFF; CONFIG_BLOB_LEN];
+                    Ok(())
+                }
+            }
+        }
+
+        fn write(&mut self, buffer: &[u8; CONFIG_BLOB_LEN]) -> Result<(), Self::Error> {
+            self.bytes = Some(*buffer);
+            Ok(())
+        }
+    }
+
+    fn sample_config() -> PersistedConfig {
+        PersistedConfig::new(
+            ControlGains::PID(1.0, 0.5, 0.25),
+            0.75,
+            (-1.0, 1.0),
+            (-5.0, 5.0),
+            &[(0.0, 0.1), (1.0, 0.9)],
+            Some([0.1, 0.2, 0.3, 0.4]),
+        )
+    }
+
+    #[test]
+    fn round_trips_through_a_backend() {
+        let mut store = ConfigStore::new(MemoryBackend { bytes: None });
+        let config = sample_config();
+        store.save(&config).unwrap();
+
+        let loaded = store.load(sample_config());
+        assert!(
+            matches!(loaded.gains, ControlGains::PID(k_p, k_i, k_d) if k_p == 1.0 && k_i == 0.5 && k_d == 0.25)
+        );
+        assert_eq!(loaded.setpoint, 0.75);
+        assert_eq!(loaded.output_clamp, (-1.0, 1.0));
+        assert_eq!(loaded.accumulator_clamp, (-5.0, 5.0));
+        assert_eq!(loaded.polynomial_correction, Some([0.1, 0.2, 0.3, 0.4]));
+    }
+
+    #[test]
+    fn falls_back_to_defaults_on_an_unwritten_backend() {
+        let mut store = ConfigStore::new(MemoryBackend { bytes: None });
+        let defaults = sample_config();
+        let loaded = store.load(sample_config());
+        assert!(
+            matches!((loaded.gains, defaults.gains), (ControlGains::PID(a, b, c), ControlGains::PID(d, e, f)) if a == d && b == e && c == f)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_defaults_on_a_corrupt_blob() {
+        let mut store = ConfigStore::new(MemoryBackend { bytes: None });
+        store.save(&sample_config()).unwrap();
+        // Flip a byte in the middle of the blob without touching its CRC.
+        if let Some(bytes) = &mut store.backend.bytes {
+            bytes[10] ^= 0xFF;
+        }
+
+        let defaults = sample_config();
+        let loaded = store.load(sample_config());
+        assert_eq!(loaded.setpoint, defaults.setpoint);
+    }
+
+    /// A mock NOR flash with a write granularity (512 bytes) larger
+    /// than a [`CONFIG_BLOB_LEN`] blob, to exercise the padding path
+    struct MockFlash {
+        data: Vec<u8>,
+    }
+
+    impl embedded_storage::nor_flash::ErrorType for MockFlash {
+        type Error = std::convert::Infallible;
+    }
+
+    impl embedded_storage::nor_flash::ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            bytes.copy_from_slice(&self.data[start..start + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl embedded_storage::nor_flash::NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 512;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            for byte in &mut self.data[from as usize..to as usize] {
+                *byte = 0xFF;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            self.data[start..start + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pads_writes_to_a_flash_write_granularity_larger_than_the_blob() {
+        let flash = MockFlash {
+            data: vec![0xFFu8; 8192],
+        };
+        let mut store = ConfigStore::new(NorFlashBackend::new(flash, 0));
+        store.save(&sample_config()).unwrap();
+
+        let loaded = store.load(sample_config());
+        assert_eq!(loaded.setpoint, 0.75);
+    }
+}
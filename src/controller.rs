@@ -1,21 +1,31 @@
-/// A simple PI servo controller for a pneumatic muscle cylinder
+/// A simple PID servo controller for a pneumatic muscle cylinder
 ///
 /// For the integration, this maintains an accumulator that tracks
-/// error.
+/// error.  The accumulator uses conditional integration for
+/// anti-windup: once the output saturates against the clamp, error
+/// that would deepen the saturation is no longer integrated, so the
+/// accumulator can't wind up while the cylinder is pinned against an
+/// end stop.
 ///
-/// For a primer on how PI controllers work, see
+/// For a primer on how PID controllers work, see
 /// <https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller>
 pub struct CylinderPositionController {
     k_p: f64,
     k_i: f64,
+    k_d: f64,
     setpoint: f64,
     error_accumulator: f64,
+    prev_measurement: Option<f64>,
+    output_clamp: (f64, f64),
+    accumulator_clamp: (f64, f64),
 }
 
 /// The gains and the controller type you'd like
+#[derive(Clone, Copy)]
 pub enum ControlGains {
     P(f64),
     PI(f64, f64),
+    PID(f64, f64, f64),
 }
 
 impl CylinderPositionController {
@@ -25,16 +35,27 @@ impl CylinderPositionController {
     /// to get reasonable results.  See e.g.
     /// <https://en.wikipedia.org/wiki/Ziegler%E2%80%93Nichols_method>
     /// for a procedure for how to select gains.
+    ///
+    /// The output clamp defaults to `[-1.0, 1.0]` to match the
+    /// EL2042 stage; the accumulator is unclamped by default since
+    /// conditional integration already prevents windup.  Both can be
+    /// overridden with [`Self::set_output_clamp`] and
+    /// [`Self::set_accumulator_clamp`].
     pub fn new(gains: ControlGains, setpoint: f64) -> Self {
-        let (k_p, k_i) = match gains {
-            ControlGains::P(k_p) => (k_p, 0.0),
-            ControlGains::PI(k_p, k_i) => (k_p, k_i),
+        let (k_p, k_i, k_d) = match gains {
+            ControlGains::P(k_p) => (k_p, 0.0, 0.0),
+            ControlGains::PI(k_p, k_i) => (k_p, k_i, 0.0),
+            ControlGains::PID(k_p, k_i, k_d) => (k_p, k_i, k_d),
         };
         Self {
             k_p,
             k_i,
+            k_d,
             setpoint,
             error_accumulator: 0.0,
+            prev_measurement: None,
+            output_clamp: (-1.0, 1.0),
+            accumulator_clamp: (f64::NEG_INFINITY, f64::INFINITY),
         }
     }
 
@@ -47,14 +68,76 @@ impl CylinderPositionController {
         self
     }
 
-    /// Generate a control signal with a PI controller
+    /// The value this controller currently regulates toward
+    pub fn setpoint(&self) -> f64 {
+        self.setpoint
+    }
+
+    /// The clamp currently applied to the output signal
+    pub fn output_clamp(&self) -> (f64, f64) {
+        self.output_clamp
+    }
+
+    /// Override the clamp applied to the output signal
+    ///
+    /// Defaults to `[-1.0, 1.0]`.
+    pub fn set_output_clamp(&mut self, min: f64, max: f64) -> &mut Self {
+        self.output_clamp = (min, max);
+        self
+    }
+
+    /// The clamp currently applied to the integration accumulator
+    pub fn accumulator_clamp(&self) -> (f64, f64) {
+        self.accumulator_clamp
+    }
+
+    /// Override the clamp applied to the integration accumulator
     ///
-    /// It's assumed, but not enforced, that measurement signal is in
-    /// the interval [0.0, 1.0].
-    pub fn control_signal(&mut self, measurement_signal: f64) -> f64 {
+    /// Defaults to unclamped, since conditional integration already
+    /// keeps the accumulator from winding up.
+    pub fn set_accumulator_clamp(&mut self, min: f64, max: f64) -> &mut Self {
+        self.accumulator_clamp = (min, max);
+        self
+    }
+
+    /// Generate a control signal with a P, PI, or PID controller
+    ///
+    /// `dt` is the elapsed time in seconds since the last call, used
+    /// to time-scale the integral and derivative terms.  It's
+    /// assumed, but not enforced, that measurement signal is in the
+    /// interval [0.0, 1.0].
+    ///
+    /// The derivative term is computed on the negated derivative of
+    /// the measurement rather than of the error, so a setpoint step
+    /// doesn't cause a derivative kick.
+    pub fn control_signal(&mut self, measurement_signal: f64, dt: f64) -> f64 {
         let error: f64 = self.setpoint - measurement_signal;
-        self.error_accumulator += error;
-        self.k_p * error + self.k_i * self.error_accumulator
+
+        let measurement_derivative = match self.prev_measurement {
+            Some(prev_measurement) => -(measurement_signal - prev_measurement) / dt,
+            None => 0.0,
+        };
+        self.prev_measurement = Some(measurement_signal);
+
+        let proportional = self.k_p * error;
+        let derivative = self.k_d * measurement_derivative;
+
+        // Anti-windup via conditional integration: if the output is
+        // already saturated and this error would push it further
+        // into saturation, don't integrate the error this tick.
+        let output_before_integration =
+            proportional + self.k_i * self.error_accumulator + derivative;
+        let saturated_high = output_before_integration > self.output_clamp.1;
+        let saturated_low = output_before_integration < self.output_clamp.0;
+        let would_deepen_saturation =
+            (saturated_high && error > 0.0) || (saturated_low && error < 0.0);
+        if !would_deepen_saturation {
+            self.error_accumulator = (self.error_accumulator + error * dt)
+                .clamp(self.accumulator_clamp.0, self.accumulator_clamp.1);
+        }
+
+        (proportional + self.k_i * self.error_accumulator + derivative)
+            .clamp(self.output_clamp.0, self.output_clamp.1)
     }
 }
 
@@ -81,17 +164,17 @@ mod tests {
     #[test]
     fn controller_drives_in_the_correct_direction() {
         let mut controller = CylinderPositionController::new(ControlGains::P(1.0), 0.5);
-        assert_op!(>, controller.control_signal(0.0), 0.0);
-        assert_op!(<, controller.control_signal(1.0), 0.0);
-        assert_eq!(controller.control_signal(0.5), 0.0);
+        assert_op!(>, controller.control_signal(0.0, 1.0), 0.0);
+        assert_op!(<, controller.control_signal(1.0, 1.0), 0.0);
+        assert_eq!(controller.control_signal(0.5, 1.0), 0.0);
     }
 
     #[test]
     fn controller_setpoint_changes() {
         let mut controller = CylinderPositionController::new(ControlGains::P(1.0), 0.5);
-        assert_op!(<, controller.new_setpoint(0.0).control_signal(0.5), 0.0);
-        assert_eq!(controller.new_setpoint(0.5).control_signal(0.5), 0.0);
-        assert_op!(>, controller.new_setpoint(1.0).control_signal(0.5), 0.0);
+        assert_op!(<, controller.new_setpoint(0.0).control_signal(0.5, 1.0), 0.0);
+        assert_eq!(controller.new_setpoint(0.5).control_signal(0.5, 1.0), 0.0);
+        assert_op!(>, controller.new_setpoint(1.0).control_signal(0.5, 1.0), 0.0);
     }
 
     #[test]
@@ -101,8 +184,8 @@ mod tests {
             let k = k as f64;
             assert!(approx_eq!(
                 f64,
-                controller.control_signal(0.1) * k,
-                controller.control_signal(k * 0.1),
+                controller.control_signal(0.1, 1.0) * k,
+                controller.control_signal(k * 0.1, 1.0),
                 ulps = 2
             ));
         }
@@ -111,8 +194,8 @@ mod tests {
             let k = k as f64;
             assert!(approx_eq!(
                 f64,
-                controller.control_signal(0.9) * k,
-                controller.control_signal(1.0 - (k * 0.1)),
+                controller.control_signal(0.9, 1.0) * k,
+                controller.control_signal(1.0 - (k * 0.1), 1.0),
                 ulps = 2
             ));
         }
@@ -124,14 +207,14 @@ mod tests {
         let controller = controller.new_setpoint(0.0);
         let mut previous_control_signal: f64 = 0.0;
         for _ in 0..10 {
-            let current_control_signal = controller.control_signal(0.1);
+            let current_control_signal = controller.control_signal(0.1, 1.0);
             assert_op!(>, current_control_signal.abs(), previous_control_signal.abs());
             previous_control_signal = current_control_signal;
         }
         let controller = controller.new_setpoint(1.0);
         let mut previous_control_signal: f64 = 0.0;
         for _ in 0..10 {
-            let current_control_signal = controller.control_signal(0.9);
+            let current_control_signal = controller.control_signal(0.9, 1.0);
             assert_op!(>, current_control_signal.abs(), previous_control_signal.abs());
             previous_control_signal = current_control_signal;
         }
@@ -139,17 +222,69 @@ mod tests {
 
     #[test]
     fn control_increases_with_higher_gains() {
-        // proportional gain
+        // proportional gain; kept small enough that neither output clamp saturates
         let mut controller0 = CylinderPositionController::new(ControlGains::P(1.0), 0.0);
         let mut controller1 = CylinderPositionController::new(ControlGains::P(10.0), 0.0);
 
-        assert_op!(>, controller1.control_signal(1.0).abs(), controller0.control_signal(1.0).abs());
+        assert_op!(
+            >,
+            controller1.control_signal(0.05, 1.0).abs(),
+            controller0.control_signal(0.05, 1.0).abs()
+        );
 
         // integral gain
         let mut controller0 = CylinderPositionController::new(ControlGains::PI(1.0, 1.0), 0.0);
         let mut controller1 = CylinderPositionController::new(ControlGains::PI(1.0, 10.0), 0.0);
-        let _ = controller0.control_signal(1.0);
-        let _ = controller1.control_signal(1.0);
-        assert_op!(>, controller1.control_signal(1.0).abs(), controller0.control_signal(1.0).abs());
+        let _ = controller0.control_signal(0.05, 1.0);
+        let _ = controller1.control_signal(0.05, 1.0);
+        assert_op!(
+            >,
+            controller1.control_signal(0.05, 1.0).abs(),
+            controller0.control_signal(0.05, 1.0).abs()
+        );
+    }
+
+    #[test]
+    fn derivative_opposes_fast_approach_but_not_setpoint_steps() {
+        let mut controller = CylinderPositionController::new(ControlGains::PID(1.0, 0.0, 1.0), 0.5);
+        // No prior measurement yet, so the derivative term is zero and
+        // the signal is pure proportional.
+        let proportional_only = controller.control_signal(0.0, 1.0);
+        assert_eq!(proportional_only, 0.5);
+
+        // The measurement now moves rapidly toward the setpoint; the
+        // derivative term should pull the output down, not spike it,
+        // since it reacts to the measurement rather than the error.
+        let with_derivative = controller.control_signal(0.5, 1.0);
+        assert_op!(<, with_derivative, 0.5);
+
+        // A setpoint step with the measurement held still leaves the
+        // derivative term at zero, so the signal is pure proportional.
+        let after_setpoint_step = controller.new_setpoint(1.0).control_signal(0.5, 1.0);
+        assert_eq!(after_setpoint_step, 0.5);
+    }
+
+    #[test]
+    fn accumulator_stops_growing_once_output_saturates() {
+        let mut controller = CylinderPositionController::new(ControlGains::PI(1.0, 1.0), 1.0);
+        for _ in 0..20 {
+            controller.control_signal(0.0, 1.0);
+        }
+        let saturated_signal = controller.control_signal(0.0, 1.0);
+        assert_eq!(saturated_signal, 1.0);
+
+        // Recovering after overshoot should act immediately rather than
+        // unwinding a runaway accumulator first.
+        assert_op!(<, controller.control_signal(1.1, 1.0), 1.0);
+    }
+
+    #[test]
+    fn integration_is_scaled_by_dt() {
+        let mut controller0 = CylinderPositionController::new(ControlGains::PI(0.0, 1.0), 1.0);
+        let mut controller1 = CylinderPositionController::new(ControlGains::PI(0.0, 1.0), 1.0);
+
+        let slow_tick = controller0.control_signal(0.0, 0.1);
+        let fast_tick = controller1.control_signal(0.0, 1.0);
+        assert_op!(<, slow_tick.abs(), fast_tick.abs());
     }
 }
@@ -0,0 +1,87 @@
+/// Which solenoid, if either, a [`DeltaSigmaModulator`] wants
+/// energized this tick
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValveDrive {
+    Hold,
+    Inflate,
+    Deflate,
+}
+
+/// Turns a continuous `[-1.0, 1.0]` control signal into a
+/// time-proportioned drive for a two-state (inflate/deflate) valve
+///
+/// This is a first-order delta-sigma (error-feedback) modulator: each
+/// tick it adds the signal's magnitude to an accumulator, and once
+/// that crosses `1.0` it emits one "on" tick and subtracts `1.0` back
+/// out. Averaged over a window, the fraction of "on" ticks converges
+/// on the requested magnitude, spreading the drive across several
+/// cycles instead of collapsing it through a deadband the way a
+/// three-state bang-bang output would.
+///
+/// For a primer on delta-sigma modulation, see
+/// <https://en.wikipedia.org/wiki/Delta-sigma_modulation>
+#[derive(Default)]
+pub struct DeltaSigmaModulator {
+    accumulator: f64,
+}
+
+impl DeltaSigmaModulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advance the modulator by one tick, returning this tick's drive
+    pub fn drive(&mut self, control_signal: f64) -> ValveDrive {
+        self.accumulator += control_signal.abs();
+        if self.accumulator < 1.0 {
+            return ValveDrive::Hold;
+        }
+        self.accumulator -= 1.0;
+        if control_signal > 0.0 {
+            ValveDrive::Inflate
+        } else {
+            ValveDrive::Deflate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_duty_matches_requested_magnitude_over_a_window() {
+        let mut modulator = DeltaSigmaModulator::new();
+        let control_signal = 0.3;
+        let ticks = 1000;
+        let on_ticks = (0..ticks)
+            .filter(|_| modulator.drive(control_signal) != ValveDrive::Hold)
+            .count();
+        let duty = on_ticks as f64 / ticks as f64;
+        assert!((duty - control_signal).abs() < 0.01);
+    }
+
+    #[test]
+    fn zero_signal_always_holds() {
+        let mut modulator = DeltaSigmaModulator::new();
+        for _ in 0..100 {
+            assert_eq!(modulator.drive(0.0), ValveDrive::Hold);
+        }
+    }
+
+    #[test]
+    fn full_scale_signal_is_always_on() {
+        let mut modulator = DeltaSigmaModulator::new();
+        for _ in 0..10 {
+            assert_ne!(modulator.drive(1.0), ValveDrive::Hold);
+        }
+    }
+
+    #[test]
+    fn negative_signal_deflates_rather_than_inflates() {
+        let mut modulator = DeltaSigmaModulator::new();
+        for _ in 0..10 {
+            assert_ne!(modulator.drive(-1.0), ValveDrive::Inflate);
+        }
+    }
+}
@@ -13,6 +13,11 @@
 //! ```ps
 //! $env:RUST_LOG="debug" ; cargo run --example ek1100 --release -- '\Device\NPF_{FF0ACEE6-E8CD-48D5-A399-619CD2340465}'
 //! ```
+//!
+//! Once running, type a pose as six space-separated numbers (`x y z
+//! roll pitch yaw`) and press enter to drive the platform there, or
+//! type `tune` to auto-tune leg 0's gains and apply them to all six
+//! legs.
 
 use std::{sync::Arc, time::Duration};
 
@@ -25,7 +30,12 @@ use ethercrab::{
 use ethercrab_wire::EtherCrabWireRead;
 use tokio::time::MissedTickBehavior;
 
+mod auto_tuner;
+mod config_store;
 mod controller;
+mod delta_sigma;
+mod position_sensor;
+mod stewart_platform;
 
 /// Maximum number of slaves that can be stored. This must be a power of 2 greater than 1.
 const MAX_SLAVES: usize = 16;
@@ -35,6 +45,10 @@ const MAX_PDU_DATA: usize = PduStorage::element_size(1100);
 const MAX_FRAMES: usize = 16;
 /// Maximum total PDI length.
 const PDI_LEN: usize = 64;
+/// Which leg an auto-tune relay-feedback experiment drives; the
+/// derived gains are then applied to all six legs, since they're
+/// typically identical hardware.
+const TUNE_LEG: usize = 0;
 
 static PDU_STORAGE: PduStorage<MAX_FRAMES, MAX_PDU_DATA> = PduStorage::new();
 
@@ -59,9 +73,48 @@ struct El3062Reading {
     value: u16,
 }
 
-struct SetpointUpdate {
+struct PoseUpdate {
     ready: bool,
-    setpoint: f64,
+    pose: stewart_platform::Pose,
+}
+
+/// Parse "x y z roll pitch yaw" (whitespace-separated) into a pose
+fn parse_pose(input: &str) -> Option<stewart_platform::Pose> {
+    let mut numbers = input.split_whitespace();
+    let mut next_number = move || numbers.next()?.parse::<f64>().ok();
+    Some(stewart_platform::Pose {
+        translation: [next_number()?, next_number()?, next_number()?],
+        roll: next_number()?,
+        pitch: next_number()?,
+        yaw: next_number()?,
+    })
+}
+
+/// Representative hexagonal leg geometry for this demo rig; replace
+/// with the real platform's anchor points and stroke limits.
+fn demo_leg_geometry() -> [stewart_platform::LegGeometry; 6] {
+    let base_radius = 0.5;
+    let platform_radius = 0.3;
+    std::array::from_fn(|i| {
+        let angle = i as f64 * std::f64::consts::PI / 3.0;
+        stewart_platform::LegGeometry {
+            base_anchor: [base_radius * angle.cos(), base_radius * angle.sin(), 0.0],
+            platform_anchor: [
+                platform_radius * angle.cos(),
+                platform_radius * angle.sin(),
+                0.0,
+            ],
+            min_stroke: 0.3,
+            max_stroke: 0.5,
+        }
+    })
+}
+
+/// Whether the loop is regulating the setpoint normally, or running a
+/// relay-feedback experiment to derive gains
+enum ControlMode {
+    Regulating,
+    AutoTuning(auto_tuner::AutoTuner),
 }
 
 #[tokio::main]
@@ -135,17 +188,52 @@ async fn main() -> Result<(), Error> {
     signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&shutdown))
         .expect("Register hook");
 
-    let initial_setpoint = 1.0;
-    let setpoint: std::sync::Arc<std::sync::Mutex<SetpointUpdate>> =
-        std::sync::Arc::new(std::sync::Mutex::new(SetpointUpdate {
-            setpoint: initial_setpoint,
-            ready: false,
-        }));
-    spawn_interactive_tty_channel(setpoint.clone());
-    let mut controller = controller::CylinderPositionController::new(
+    let mut config_store = config_store::ConfigStore::new(config_store::FileBackend::new(
+        std::env::var("STEWART_CONFIG_PATH").unwrap_or_else(|_| "stewart-config.bin".to_string()),
+    ));
+    let default_config = config_store::PersistedConfig::new(
         controller::ControlGains::P(1.0),
-        initial_setpoint,
+        0.5,
+        (-1.0, 1.0),
+        (f64::NEG_INFINITY, f64::INFINITY),
+        &[(0.0, 0.0), (1.0, 1.0)],
+        None,
     );
+    let mut active_config = config_store.load(default_config);
+
+    let initial_pose = stewart_platform::Pose {
+        translation: [0.0, 0.0, 0.4],
+        roll: 0.0,
+        pitch: 0.0,
+        yaw: 0.0,
+    };
+    let mut platform =
+        stewart_platform::StewartPlatform::new(demo_leg_geometry(), active_config.gains);
+    for leg in 0..6 {
+        platform
+            .leg_controller(leg)
+            .set_output_clamp(active_config.output_clamp.0, active_config.output_clamp.1)
+            .set_accumulator_clamp(
+                active_config.accumulator_clamp.0,
+                active_config.accumulator_clamp.1,
+            );
+    }
+    platform
+        .set_pose(&initial_pose)
+        .expect("initial pose should be within the demo rig's reach");
+
+    let pose_update: std::sync::Arc<std::sync::Mutex<PoseUpdate>> =
+        std::sync::Arc::new(std::sync::Mutex::new(PoseUpdate {
+            pose: initial_pose,
+            ready: false,
+        }));
+    let auto_tune_requested = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    spawn_interactive_tty_channel(pose_update.clone(), auto_tune_requested.clone());
+    let mut mode = ControlMode::Regulating;
+    let mut modulators: [delta_sigma::DeltaSigmaModulator; 6] =
+        std::array::from_fn(|_| delta_sigma::DeltaSigmaModulator::new());
+    let position_sensors: [position_sensor::PositionSensor; 6] =
+        std::array::from_fn(|_| position_sensor::PositionSensor::new(active_config.calibration()));
     loop {
         // graceful shutdown on ^C
         if shutdown.load(std::sync::atomic::Ordering::Relaxed) {
@@ -154,36 +242,103 @@ async fn main() -> Result<(), Error> {
         }
         group.tx_rx(&client).await.expect("TX/RX");
 
-        if setpoint.lock().unwrap().ready {
-            let new_setpoint = setpoint.lock().unwrap().setpoint;
-            controller.new_setpoint(new_setpoint);
-            setpoint.lock().unwrap().ready = false;
+        if pose_update.lock().unwrap().ready {
+            let new_pose = pose_update.lock().unwrap().pose;
+            if let Err(unreachable) = platform.set_pose(&new_pose) {
+                log::warn!("Ignoring unreachable pose: {unreachable}");
+            }
+            pose_update.lock().unwrap().ready = false;
         };
 
-        let mut measurement_signal: Option<f64> = None;
-        if let Some(el3062) = group.iter(&client).find(|slave| slave.name() == "EL3062") {
+        if auto_tune_requested.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            if matches!(mode, ControlMode::Regulating) {
+                log::info!(
+                    "Starting relay-feedback auto-tune on leg {TUNE_LEG}, hold the platform steady..."
+                );
+                mode = ControlMode::AutoTuning(auto_tuner::AutoTuner::new(
+                    platform.leg_controller(TUNE_LEG).setpoint(),
+                    0.3,
+                    0.02,
+                    auto_tuner::AutoTuneTarget::PID,
+                    5,
+                    Duration::from_secs(60),
+                ));
+            } else {
+                log::warn!("Auto-tune already in progress");
+            }
+        }
+
+        let mut measurements: [Option<f64>; 6] = [None; 6];
+        for (leg, el3062) in group
+            .iter(&client)
+            .filter(|slave| slave.name() == "EL3062")
+            .take(6)
+            .enumerate()
+        {
             let (i, _) = el3062.io_raw();
             if let Ok(channel1) = El3062Reading::unpack_from_slice(&i[..4]) {
-                measurement_signal = Some(channel1.value as f64 / u16::MAX as f64);
+                measurements[leg] = position_sensors[leg].read(
+                    channel1.value,
+                    channel1.underrange,
+                    channel1.overrange,
+                    channel1.error,
+                );
             }
         }
 
-        let control_signal = if let Some(measurement_signal) = measurement_signal {
-            controller.control_signal(measurement_signal)
-        } else {
-            0.0
-        };
-        if let Some(mut el2042) = group.iter(&client).find(|slave| slave.name() == "EL2042") {
-            let (_, o) = el2042.io_raw_mut();
-            let deadband_half_width = 0.01;
-            o[0] = if control_signal > deadband_half_width {
-                0b10
-            } else if control_signal < -deadband_half_width {
-                0b01
+        let mut control_signals = [0.0; 6];
+        for (leg, measurement) in measurements.into_iter().enumerate() {
+            let Some(measurement) = measurement else {
+                continue;
+            };
+            control_signals[leg] = if leg == TUNE_LEG {
+                match &mut mode {
+                    ControlMode::Regulating => platform
+                        .leg_controller(leg)
+                        .control_signal(measurement, 0.01),
+                    ControlMode::AutoTuning(tuner) => {
+                        match tuner.step(measurement, 0.01) {
+                            auto_tuner::AutoTuneStatus::InProgress => tuner.relay_output(),
+                            auto_tuner::AutoTuneStatus::Converged(gains) => {
+                                log::info!("Auto-tune converged, applying the derived gains to all six legs");
+                                platform.set_gains(gains);
+                                active_config.gains = gains;
+                                if let Err(error) = config_store.save(&active_config) {
+                                    log::warn!("Failed to persist auto-tuned gains: {error:?}");
+                                }
+                                mode = ControlMode::Regulating;
+                                0.0
+                            }
+                            auto_tuner::AutoTuneStatus::TimedOut => {
+                                log::warn!(
+                                "Auto-tune timed out without a stable oscillation, keeping prior gains"
+                            );
+                                mode = ControlMode::Regulating;
+                                0.0
+                            }
+                        }
+                    }
+                }
             } else {
-                0b00
+                platform
+                    .leg_controller(leg)
+                    .control_signal(measurement, 0.01)
             };
-        };
+        }
+
+        for (leg, mut el2042) in group
+            .iter(&client)
+            .filter(|slave| slave.name() == "EL2042")
+            .take(6)
+            .enumerate()
+        {
+            let (_, o) = el2042.io_raw_mut();
+            o[0] = match modulators[leg].drive(control_signals[leg]) {
+                delta_sigma::ValveDrive::Inflate => 0b10,
+                delta_sigma::ValveDrive::Deflate => 0b01,
+                delta_sigma::ValveDrive::Hold => 0b00,
+            };
+        }
 
         tick_interval.tick().await;
     }
@@ -195,18 +350,27 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
-fn spawn_interactive_tty_channel(setpoint: std::sync::Arc<std::sync::Mutex<SetpointUpdate>>) {
+fn spawn_interactive_tty_channel(
+    pose_update: std::sync::Arc<std::sync::Mutex<PoseUpdate>>,
+    auto_tune_requested: Arc<std::sync::atomic::AtomicBool>,
+) {
     std::thread::spawn(move || loop {
         let mut buffer = String::new();
         std::io::stdin().read_line(&mut buffer).unwrap();
-        match buffer.trim().parse() {
-            Ok(new_setpoint) => {
-                *setpoint.lock().unwrap() = SetpointUpdate {
-                    setpoint: new_setpoint,
-                    ready: true,
-                }
+        let input = buffer.trim();
+
+        if input.eq_ignore_ascii_case("tune") {
+            auto_tune_requested.store(true, std::sync::atomic::Ordering::Relaxed);
+            continue;
+        }
+
+        match parse_pose(input) {
+            Some(pose) => {
+                *pose_update.lock().unwrap() = PoseUpdate { pose, ready: true };
             }
-            Err(_) => eprintln!("setpoint must be a real number"),
+            None => eprintln!(
+                "pose must be six numbers \"x y z roll pitch yaw\", or \"tune\" to auto-tune"
+            ),
         }
     });
 }
@@ -0,0 +1,144 @@
+/// A piecewise-linear calibration table paired with an optional
+/// low-order polynomial correction, mapping a raw normalized sensor
+/// reading onto a calibrated position in `[0.0, 1.0]`
+///
+/// The table is `(raw, position)` pairs sorted by `raw`; a reading
+/// between two entries is linearly interpolated, and a reading
+/// outside the table's domain is clamped to its nearest entry. If
+/// set, the polynomial correction `c0 + c1*x + c2*x^2 + c3*x^3` is
+/// applied to the interpolated value before the final clamp to
+/// `[0.0, 1.0]`.
+pub struct Calibration {
+    table: Vec<(f64, f64)>,
+    polynomial: Option<[f64; 4]>,
+}
+
+impl Calibration {
+    /// Build a calibration from a lookup table sorted by raw value
+    ///
+    /// Panics if `table` is empty.
+    pub fn new(table: Vec<(f64, f64)>) -> Self {
+        assert!(!table.is_empty(), "calibration table must not be empty");
+        Self {
+            table,
+            polynomial: None,
+        }
+    }
+
+    /// Apply an additional `c0 + c1*x + c2*x^2 + c3*x^3` correction
+    /// on top of the table lookup
+    pub fn set_polynomial_correction(&mut self, coefficients: [f64; 4]) -> &mut Self {
+        self.polynomial = Some(coefficients);
+        self
+    }
+
+    /// Map a raw normalized reading through the table and optional
+    /// polynomial correction, clamped to `[0.0, 1.0]`
+    fn apply(&self, raw: f64) -> f64 {
+        let interpolated = self.interpolate(raw);
+        let corrected = match self.polynomial {
+            Some([c0, c1, c2, c3]) => {
+                c0 + c1 * interpolated + c2 * interpolated.powi(2) + c3 * interpolated.powi(3)
+            }
+            None => interpolated,
+        };
+        corrected.clamp(0.0, 1.0)
+    }
+
+    /// Linearly interpolate `raw` against the table, clamping to its
+    /// domain
+    fn interpolate(&self, raw: f64) -> f64 {
+        let last = self.table.len() - 1;
+        if raw <= self.table[0].0 {
+            return self.table[0].1;
+        }
+        if raw >= self.table[last].0 {
+            return self.table[last].1;
+        }
+        let upper = self.table.partition_point(|&(x, _)| x < raw);
+        let (x0, y0) = self.table[upper - 1];
+        let (x1, y1) = self.table[upper];
+        y0 + (y1 - y0) * (raw - x0) / (x1 - x0)
+    }
+}
+
+impl Default for Calibration {
+    /// The identity calibration: the raw reading passes straight
+    /// through unchanged
+    fn default() -> Self {
+        Self::new(vec![(0.0, 0.0), (1.0, 1.0)])
+    }
+}
+
+/// Wraps a raw EL3062 channel reading, rejecting faulted samples and
+/// applying a [`Calibration`] to the rest
+pub struct PositionSensor {
+    calibration: Calibration,
+}
+
+impl PositionSensor {
+    /// Build a sensor around a calibration, e.g. one loaded at
+    /// startup
+    pub fn new(calibration: Calibration) -> Self {
+        Self { calibration }
+    }
+
+    /// Turn a raw EL3062 channel reading into a calibrated position,
+    /// or `None` if the channel reports a fault
+    ///
+    /// `error`, `underrange`, and `overrange` are the channel's PDO
+    /// status bits; if any of them are set, the raw value isn't
+    /// trustworthy and no position is returned.
+    pub fn read(&self, value: u16, underrange: bool, overrange: bool, error: bool) -> Option<f64> {
+        if error || underrange || overrange {
+            return None;
+        }
+        Some(self.calibration.apply(value as f64 / u16::MAX as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fault_flags_return_none() {
+        let sensor = PositionSensor::new(Calibration::default());
+        assert_eq!(sensor.read(12345, true, false, false), None);
+        assert_eq!(sensor.read(12345, false, true, false), None);
+        assert_eq!(sensor.read(12345, false, false, true), None);
+    }
+
+    #[test]
+    fn identity_calibration_passes_through_linearly() {
+        let sensor = PositionSensor::new(Calibration::default());
+        let position = sensor.read(u16::MAX / 2, false, false, false).unwrap();
+        assert!((position - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn interpolates_between_table_points() {
+        let calibration = Calibration::new(vec![(0.0, 0.1), (0.5, 0.4), (1.0, 0.9)]);
+        let sensor = PositionSensor::new(calibration);
+        let position = sensor.read(u16::MAX / 4, false, false, false).unwrap();
+        assert!((position - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn out_of_domain_clamps_to_table_ends() {
+        let calibration = Calibration::new(vec![(0.2, 0.0), (0.8, 1.0)]);
+        let sensor = PositionSensor::new(calibration);
+        assert_eq!(sensor.read(0, false, false, false), Some(0.0));
+        assert_eq!(sensor.read(u16::MAX, false, false, false), Some(1.0));
+    }
+
+    #[test]
+    fn polynomial_correction_is_applied_and_then_clamped() {
+        let mut calibration = Calibration::default();
+        calibration.set_polynomial_correction([0.5, 0.0, 0.0, 0.0]);
+        let sensor = PositionSensor::new(calibration);
+        // The table maps this raw value to 1.0, but the polynomial
+        // correction replaces that with the constant 0.5.
+        assert_eq!(sensor.read(u16::MAX, false, false, false), Some(0.5));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::controller::{ControlGains, CylinderPositionController};
+
+/// A point or free vector in whichever frame it's documented against
+pub type Vector3 = [f64; 3];
+
+/// A commanded 6-DOF pose of the platform frame relative to the base
+/// frame
+///
+/// Orientation is given as roll/pitch/yaw about the base frame's
+/// fixed axes (applied in that order: roll about X, then pitch about
+/// Y, then yaw about Z), i.e. `R = Rz(yaw)·Ry(pitch)·Rx(roll)`.
+#[derive(Clone, Copy)]
+pub struct Pose {
+    pub translation: Vector3,
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+/// The fixed geometry of one actuator: its anchor points in the base
+/// and platform frames, and the physical stroke length it can reach
+/// between
+#[derive(Clone, Copy)]
+pub struct LegGeometry {
+    pub base_anchor: Vector3,
+    pub platform_anchor: Vector3,
+    pub min_stroke: f64,
+    pub max_stroke: f64,
+}
+
+/// A commanded pose would take a leg outside its stroke limits
+#[derive(Debug)]
+pub struct UnreachablePose {
+    pub leg: usize,
+    pub required_length: f64,
+}
+
+impl std::fmt::Display for UnreachablePose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "leg {} would need to be {:.4} long, which is outside its stroke limits",
+            self.leg, self.required_length
+        )
+    }
+}
+
+impl std::error::Error for UnreachablePose {}
+
+/// Coordinates the six [`CylinderPositionController`]s of a Stewart
+/// (Gough-Stewart) platform, converting a commanded pose into their
+/// setpoints via inverse kinematics
+///
+/// For a primer on Stewart platform inverse kinematics, see
+/// <https://en.wikipedia.org/wiki/Stewart_platform#Inverse_kinematics>
+pub struct StewartPlatform {
+    legs: [LegGeometry; 6],
+    controllers: [CylinderPositionController; 6],
+}
+
+impl StewartPlatform {
+    /// Build a platform from its six actuators' anchor points and
+    /// stroke limits, all driven by the same gains
+    pub fn new(legs: [LegGeometry; 6], gains: ControlGains) -> Self {
+        Self {
+            legs,
+            controllers: std::array::from_fn(|_| CylinderPositionController::new(gains, 0.5)),
+        }
+    }
+
+    /// Command every actuator to regulate toward this pose
+    ///
+    /// Each leg vector is `L_i = T + R·p_i − b_i`, whose length is
+    /// mapped from `[l_min, l_max]` onto the `[0.0, 1.0]` setpoint the
+    /// controllers expect. If any leg's required length falls outside
+    /// its stroke limits, this returns an error and leaves every
+    /// controller's setpoint unchanged.
+    pub fn set_pose(&mut self, pose: &Pose) -> Result<(), UnreachablePose> {
+        let rotation = rotation_matrix(pose.roll, pose.pitch, pose.yaw);
+
+        let mut setpoints = [0.0; 6];
+        for (i, leg) in self.legs.iter().enumerate() {
+            let leg_vector = subtract(
+                add(pose.translation, multiply(rotation, leg.platform_anchor)),
+                leg.base_anchor,
+            );
+            let length = norm(leg_vector);
+            if length < leg.min_stroke || length > leg.max_stroke {
+                return Err(UnreachablePose {
+                    leg: i,
+                    required_length: length,
+                });
+            }
+            setpoints[i] = (length - leg.min_stroke) / (leg.max_stroke - leg.min_stroke);
+        }
+
+        for (controller, setpoint) in self.controllers.iter_mut().zip(setpoints) {
+            controller.new_setpoint(setpoint);
+        }
+        Ok(())
+    }
+
+    /// Drive every gain to the same new value, keeping each
+    /// controller's current setpoint and output/accumulator clamps
+    ///
+    /// Useful after an [`AutoTuner`](crate::auto_tuner::AutoTuner) run
+    /// on one representative actuator, since all six are typically the
+    /// same hardware.
+    pub fn set_gains(&mut self, gains: ControlGains) {
+        for controller in &mut self.controllers {
+            let setpoint = controller.setpoint();
+            let output_clamp = controller.output_clamp();
+            let accumulator_clamp = controller.accumulator_clamp();
+            *controller = CylinderPositionController::new(gains, setpoint);
+            controller
+                .set_output_clamp(output_clamp.0, output_clamp.1)
+                .set_accumulator_clamp(accumulator_clamp.0, accumulator_clamp.1);
+        }
+    }
+
+    /// Generate the six control signals given the six measurements,
+    /// one per actuator in the same order the legs were configured
+    pub fn control_signals(&mut self, measurements: [f64; 6], dt: f64) -> [f64; 6] {
+        std::array::from_fn(|i| self.controllers[i].control_signal(measurements[i], dt))
+    }
+
+    /// The controller for a single leg, e.g. to drive it directly
+    /// during an auto-tune
+    pub fn leg_controller(&mut self, leg: usize) -> &mut CylinderPositionController {
+        &mut self.controllers[leg]
+    }
+}
+
+/// The rotation matrix for roll (X), then pitch (Y), then yaw (Z),
+/// each about the fixed base axes: `Rz(yaw)·Ry(pitch)·Rx(roll)`
+fn rotation_matrix(roll: f64, pitch: f64, yaw: f64) -> [[f64; 3]; 3] {
+    let (sr, cr) = roll.sin_cos();
+    let (sp, cp) = pitch.sin_cos();
+    let (sy, cy) = yaw.sin_cos();
+    [
+        [cy * cp, cy * sp * sr - sy * cr, cy * sp * cr + sy * sr],
+        [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
+        [-sp, cp * sr, cp * cr],
+    ]
+}
+
+fn multiply(matrix: [[f64; 3]; 3], vector: Vector3) -> Vector3 {
+    std::array::from_fn(|row| {
+        (0..3)
+            .map(|col| matrix[row][col] * vector[col])
+            .sum::<f64>()
+    })
+}
+
+fn add(a: Vector3, b: Vector3) -> Vector3 {
+    std::array::from_fn(|i| a[i] + b[i])
+}
+
+fn subtract(a: Vector3, b: Vector3) -> Vector3 {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+fn norm(v: Vector3) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every leg's platform anchor sits one unit out along X, so a
+    /// yaw rotation swings it through a known arc
+    fn radial_leg_geometry() -> [LegGeometry; 6] {
+        std::array::from_fn(|_| LegGeometry {
+            base_anchor: [0.0, 0.0, 0.0],
+            platform_anchor: [1.0, 0.0, 0.0],
+            min_stroke: 0.0,
+            max_stroke: 2.0,
+        })
+    }
+
+    /// Every leg's anchors coincide, so its length is just the
+    /// platform's translation magnitude
+    fn vertical_leg_geometry(min_stroke: f64, max_stroke: f64) -> [LegGeometry; 6] {
+        std::array::from_fn(|_| LegGeometry {
+            base_anchor: [0.0, 0.0, 0.0],
+            platform_anchor: [0.0, 0.0, 0.0],
+            min_stroke,
+            max_stroke,
+        })
+    }
+
+    #[test]
+    fn a_pose_maps_to_the_expected_leg_length() {
+        let mut platform = StewartPlatform::new(radial_leg_geometry(), ControlGains::P(1.0));
+        let pose = Pose {
+            translation: [0.0, 0.0, 0.0],
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: std::f64::consts::FRAC_PI_2,
+        };
+        platform.set_pose(&pose).unwrap();
+
+        // A 90 degree yaw swings the platform anchor [1, 0, 0] to
+        // [0, 1, 0], a leg length of 1.0 out of the [0.0, 2.0]
+        // stroke range, i.e. a setpoint of 0.5.
+        assert!((platform.leg_controller(0).setpoint() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn an_unreachable_pose_is_rejected_and_leaves_setpoints_unchanged() {
+        let mut platform =
+            StewartPlatform::new(vertical_leg_geometry(0.3, 0.5), ControlGains::P(1.0));
+        let reachable_pose = Pose {
+            translation: [0.0, 0.0, 0.4],
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+        };
+        platform.set_pose(&reachable_pose).unwrap();
+        let setpoint_before = platform.leg_controller(0).setpoint();
+
+        let unreachable_pose = Pose {
+            translation: [0.0, 0.0, 10.0],
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+        };
+        let error = platform.set_pose(&unreachable_pose).unwrap_err();
+        assert_eq!(error.leg, 0);
+        assert!((error.required_length - 10.0).abs() < 1e-9);
+        assert_eq!(platform.leg_controller(0).setpoint(), setpoint_before);
+    }
+}